@@ -4,6 +4,7 @@ use std::io::Write;
 
 use cpu::{Register, Reg8, Reg16};
 use gb;
+use io::{SerialWriter, StdoutSerialWriter};
 
 enum ExecutionMode {
 	Running,
@@ -12,42 +13,41 @@ enum ExecutionMode {
 
 #[derive(Clone, Copy)]
 enum DebugCommand {
-	Continue, // Continue until next breakpoint
+	Continue, // Continue until next breakpoint or watchpoint
 	SetBreakpoint(u16), // Set breakpoint at said memory address (it should be the start of an instruction)
+	SetWatchpoint(u16), // Halt `Continue` when the byte at this address changes value; a write of the same value is not noticed
 	PrintRegister(Register), // Print the contents of a register
 	Quit,
 	Disassemble(u16), // Disassemble the next n instructions
+	ExamineMemory(u16, u16), // Hex-dump `count` bytes starting at an address
 	PrintCpuRegs, // Print all CPU registers
 	Step, // Execute just one CPU instruction
+	Backtrace(u16), // Print the last n executed PCs
 	LastCommand, // Repeat last command
 }
 
-/*impl Clone for DebugCommand {
-	fn clone(&self) -> DebugCommand {
-		match self {
-			DebugCommand::PrintRegister(r) => DebugCommand::PrintRegister(r),
-			_ => *self
-		}
-	}
-}
-impl Copy for DebugCommand { }*/
-
 pub struct Emulator {
 	gb: gb::GB,
 	mode: ExecutionMode,
 
 	//Debugging
 	breakpoints: HashSet<u16>,
+	watchpoints: HashSet<u16>,
 	last_command: Option<DebugCommand>,
 }
 
 impl Emulator {
 	pub fn new(boot_rom: Box<[u8]>, cart_rom: Box<[u8]>, debug: bool) -> Emulator {
+		Self::new_with_serial_writer(boot_rom, cart_rom, debug, Box::new(StdoutSerialWriter))
+	}
+
+	pub fn new_with_serial_writer(boot_rom: Box<[u8]>, cart_rom: Box<[u8]>, debug: bool, serial_writer: Box<SerialWriter>) -> Emulator {
 		Emulator {
-			gb: gb::GB::new(boot_rom, cart_rom),
+			gb: gb::GB::new(boot_rom, cart_rom, serial_writer),
 			mode: if debug {ExecutionMode::Debugging} else {ExecutionMode::Running},
 
 			breakpoints: HashSet::new(),
+			watchpoints: HashSet::new(),
 			last_command: None,
 		}
 	}
@@ -77,20 +77,68 @@ impl Emulator {
 				}
 			}
 
+			DebugCommand::SetWatchpoint(addr) => {
+				if self.watchpoints.contains(&addr) {
+					self.watchpoints.remove(&addr);
+					println!("Watchpoint at 0x{:04X} removed", addr);
+				}
+				else {
+					self.watchpoints.insert(addr);
+					println!("Watchpoint set at address 0x{:04X}", addr);
+				}
+			}
+
 			DebugCommand::Continue => {
-				while !self.breakpoints.contains(&self.gb.cpu.pc) {
-					let pc_of_inst = self.gb.cpu.pc; // Needs to be retreived before step
+				// Snapshotted so a changed byte after stepping means a watchpoint fired
+				let mut watched: Vec<(u16, u8)> = self.watchpoints.iter()
+					.map(|&addr| (addr, self.gb.read_memory_byte(addr)))
+					.collect();
+
+				while !self.breakpoints.contains(&self.gb.pc()) {
+					let pc_of_inst = self.gb.pc(); // Needs to be retreived before step
 					let inst = self.gb.step();
 					println!("  {:04X} : {}", pc_of_inst, inst);
+
+					let mut watchpoint_hit = false;
+					for watched_addr in watched.iter_mut() {
+						let new_val = self.gb.read_memory_byte(watched_addr.0);
+						if new_val != watched_addr.1 {
+							println!("Watchpoint hit: 0x{:04X} is now {:02X}", watched_addr.0, new_val);
+							watched_addr.1 = new_val;
+							watchpoint_hit = true;
+						}
+					}
+					if watchpoint_hit {
+						break;
+					}
 				}
 			}
 
 			DebugCommand::Step => {
-				let pc_of_inst = self.gb.cpu.pc; // Needs to be retreived before step
+				let pc_of_inst = self.gb.pc(); // Needs to be retreived before step
 				let inst = self.gb.step();
 				println!("  {:04X} : {}", pc_of_inst, inst);
 			}
 
+			DebugCommand::ExamineMemory(addr, count) => {
+				for i in 0 .. count {
+					if i % 16 == 0 {
+						if i != 0 {
+							println!("");
+						}
+						print!("  {:04X} :", addr.wrapping_add(i));
+					}
+					print!(" {:02X}", self.gb.read_memory_byte(addr.wrapping_add(i)));
+				}
+				println!("");
+			}
+
+			DebugCommand::Backtrace(n) => {
+				for (i, pc) in self.gb.recent_pcs().iter().take(n as usize).enumerate() {
+					println!("  #{} {:04X}", i, pc);
+				}
+			}
+
 			DebugCommand::PrintRegister(r) => {
 				match r {
 					Register::Register8(r8) => {
@@ -137,15 +185,21 @@ impl Emulator {
 			if let Some(comm) = Self::parse_debug_operation(&stdin_buffer) {
 				match comm {
 					DebugCommand::Quit => break,
+
 					DebugCommand::LastCommand => {
-						/*if let Some(last_comm) = self.last_command {
+						if let Some(last_comm) = self.last_command {
 							self.execute_debug_command(last_comm);
-						}*/
+						}
+						else {
+							println!("No previous command");
+						}
 					},
-					_ => self.execute_debug_command(comm),
-				}
 
-				self.last_command = Some(comm);
+					_ => {
+						self.execute_debug_command(comm);
+						self.last_command = Some(comm);
+					}
+				}
 			}
 
 			else {
@@ -236,6 +290,66 @@ impl Emulator {
 
 			"s" => Some(DebugCommand::Step),
 
+			"w" => {
+				if chunks.len() != 2 {
+					println!("`w' syntax: w <hex_addr>");
+					None
+				}
+				else {
+					if let Ok(addr) = u16::from_str_radix(chunks[1], 16) {
+						Some(DebugCommand::SetWatchpoint(addr))
+					}
+					else {
+						println!("Invalid address");
+						None
+					}
+				}
+			},
+
+			"x" => {
+				if chunks.len() != 2 && chunks.len() != 3 {
+					println!("`x' syntax: x <hex_addr> [count]");
+					None
+				}
+				else if let Ok(addr) = u16::from_str_radix(chunks[1], 16) {
+					if chunks.len() == 3 {
+						if let Ok(count) = u16::from_str_radix(chunks[2], 10) {
+							Some(DebugCommand::ExamineMemory(addr, count))
+						}
+						else {
+							println!("Invalid count \"{}\"", chunks[2]);
+							None
+						}
+					}
+					else {
+						Some(DebugCommand::ExamineMemory(addr, 16))
+					}
+				}
+				else {
+					println!("Invalid address");
+					None
+				}
+			},
+
+			"bt" => {
+				if chunks.len() > 2 {
+					println!("`bt' syntax: bt [n]");
+					None
+				}
+				else if chunks.len() == 2 {
+					if let Ok(n) = u16::from_str_radix(chunks[1], 10) {
+						Some(DebugCommand::Backtrace(n))
+					}
+					else {
+						println!("Invalid number of instructions \"{}\"", chunks[1]);
+						None
+					}
+				}
+				else {
+					Some(DebugCommand::Backtrace(10))
+				}
+			},
+
 			"" => Some(DebugCommand::LastCommand),
 
 			_ => None
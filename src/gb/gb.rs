@@ -1,26 +1,54 @@
 use super::Interconnect;
 use super::cpu;
+use super::ring_buffer::RingBuffer;
+use mapper;
+use io::SerialWriter;
+
+// How many recently executed PC values the debugger's `bt` command can look back through
+const PC_HISTORY_CAPACITY: usize = 32;
 
 #[derive(Debug)]
 pub struct GB {
 	cpu: cpu::Cpu,
-	interconnect: Interconnect
+	interconnect: Interconnect,
+	pc_history: RingBuffer<u16>,
 }
 
 #[allow(dead_code)]
 impl GB {
-	pub fn new(boot_rom: Box<[u8]>, cart_rom: Box<[u8]>) -> GB {
+	pub fn new(boot_rom: Box<[u8]>, cart_rom: Box<[u8]>, serial_writer: Box<SerialWriter>) -> GB {
+		// Picks the mapper off the cartridge header (byte 0x147) so ROMs
+		// larger than 32 KB or backed by external RAM load correctly; the
+		// Interconnect then routes 0x0000-0x7FFF and 0xA000-0xBFFF through it
+		// instead of indexing `cart_rom` flatly
+		let mapper = mapper::from_header(cart_rom).unwrap_or_else(|e| panic!("{}", e));
+
 		GB {
 			cpu: cpu::Cpu::default(),
-			interconnect: Interconnect::new(boot_rom, cart_rom)
+			interconnect: Interconnect::new(boot_rom, mapper, serial_writer),
+			pc_history: RingBuffer::new(PC_HISTORY_CAPACITY, 0),
 		}
 	}
 
 	pub fn step(&mut self) {
+		self.pc_history.push(self.cpu.pc);
 		self.cpu.step(&mut self.interconnect);
 	}
 
 	pub fn run(&mut self) {
 		self.cpu.run(&mut self.interconnect);
 	}
+
+	pub fn pc(&self) -> u16 {
+		self.cpu.pc
+	}
+
+	// Most recently executed PC values first, for the debugger's `bt` command
+	pub fn recent_pcs(&self) -> Vec<u16> {
+		self.pc_history.recent()
+	}
+
+	pub fn read_memory_byte(&self, addr: u16) -> u8 {
+		self.interconnect.read_byte(addr)
+	}
 }
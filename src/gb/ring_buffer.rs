@@ -0,0 +1,36 @@
+// A fixed-size, overwrite-oldest ring buffer, used to keep a rolling
+// history of recently executed PC values for post-mortem debugging
+
+#[derive(Debug)]
+pub struct RingBuffer<T: Copy> {
+	buffer: Vec<T>,
+	capacity: usize,
+	next: usize,
+	len: usize,
+}
+
+impl<T: Copy> RingBuffer<T> {
+	pub fn new(capacity: usize, fill: T) -> RingBuffer<T> {
+		RingBuffer {
+			buffer: vec![fill ; capacity],
+			capacity: capacity,
+			next: 0,
+			len: 0,
+		}
+	}
+
+	pub fn push(&mut self, val: T) {
+		self.buffer[self.next] = val;
+		self.next = (self.next + 1) % self.capacity;
+		if self.len < self.capacity {
+			self.len += 1;
+		}
+	}
+
+	// Most recently pushed value first
+	pub fn recent(&self) -> Vec<T> {
+		(0 .. self.len)
+			.map(|i| self.buffer[(self.next + self.capacity - 1 - i) % self.capacity])
+			.collect()
+	}
+}
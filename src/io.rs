@@ -1,21 +1,31 @@
+use std::io::{self, Write};
+
+use video;
+use video::PPU;
+use timer::Timer;
+
 pub struct GBIO {
 	interrupt: Interrupt,
 	sound: Sound,
 	joypad: Joypad,
 	serial: SerialData,
 	ppu: PPU,
+	timer: Timer,
+	dma: Option<DmaState>,
 
 	boot: bool
 }
 
 impl GBIO {
-	pub fn new() -> GBIO {
+	pub fn new(serial_writer: Box<SerialWriter>) -> GBIO {
 		GBIO {
 			interrupt: Interrupt::default(),
 			sound: Sound{},
 			joypad: Joypad::default(),
-			serial: SerialData{},
-			ppu: PPU{},
+			serial: SerialData::new(serial_writer),
+			ppu: PPU::default(),
+			timer: Timer::default(),
+			dma: None,
 
 			boot: true
 		}
@@ -26,17 +36,90 @@ impl GBIO {
 		self.boot
 	}
 
+	// Drives the PPU's STAT mode machine, the timer and any serial transfer
+	// forward by `cycles` dots, raising whichever of their interrupts fired
+	// in the process
+	pub fn step(&mut self, cycles: u32) {
+		let fired = self.ppu.step(cycles);
+
+		if fired.vblank {
+			self.interrupt.flag_vblank();
+		}
+		if fired.lcdstat {
+			self.interrupt.flag_lcdstat();
+		}
+
+		if self.timer.step(cycles) {
+			self.interrupt.flag_timer();
+		}
+
+		if self.serial.step(cycles) {
+			self.interrupt.flag_serial();
+		}
+	}
+
+	// The framebuffer produced by the PPU, for the frontend to draw
+	pub fn framebuffer(&self) -> &[video::Color ; video::SCREEN_PIXELS] {
+		self.ppu.framebuffer()
+	}
+
+	// IO-side half of interrupt dispatch: resolves which interrupt is next
+	// and lets the CPU acknowledge it. IME, the EI delay slot, the HALT bug
+	// and the push-PC-and-jump dispatch itself live in `Cpu::step` (cpu.rs).
+	pub fn pending_interrupt(&self) -> Option<InterruptSource> {
+		self.interrupt.pending()
+	}
+
+	// Clears the flag for `source` once the CPU has serviced it
+	pub fn acknowledge_interrupt(&mut self, source: InterruptSource) {
+		self.interrupt.acknowledge(source);
+	}
+
+	// True while an OAM DMA transfer is in flight; on real hardware the CPU
+	// can only access HRAM for the duration
+	pub fn dma_active(&self) -> bool {
+		self.dma.is_some()
+	}
+
+	// Copies one byte of the in-flight OAM DMA transfer, reading the source
+	// byte through `read_byte`. The `Interconnect` is the only thing that can
+	// see both the DMA source region and OAM, so it calls this once per
+	// machine cycle while a transfer is active.
+	pub fn step_dma<F: FnOnce(u16) -> u8>(&mut self, read_byte: F) {
+		let finished = match self.dma {
+			Some(ref mut dma) => {
+				let oam_offset = 0xA0 - dma.remaining_cycles;
+				let val = read_byte(dma.source_address());
+				self.ppu.write_sprite_entry(oam_offset, val);
+
+				dma.remaining_cycles -= 1;
+				dma.remaining_cycles == 0
+			}
+			None => false,
+		};
+
+		if finished {
+			self.dma = None;
+		}
+	}
+
 	pub fn write_byte(&mut self, addr: u8, val: u8) {
 		match addr {
 			0x00 => self.joypad.write_joypad(val),
 
-			//0x01 ... 0x03 => // Serial data transfer
+			0x01 => self.serial.write_sb(val),
+			0x02 => self.serial.write_sc(val),
 
-			//0x04 ... 0x07 => // Timer
+			0x04 => self.timer.write_div(val),
+			0x05 => self.timer.write_tima(val),
+			0x06 => self.timer.write_tma(val),
+			0x07 => self.timer.write_tac(val),
 
 			0x10 ... 0x26 => println!("Write to SOUND"),
 
-			//0x40 ... 0x4B => // PPU
+			0x40 ... 0x45 => self.ppu.write_ppu(addr, val),
+			0x46 => self.dma = Some(DmaState::new(val)),
+			0x47 ... 0x4B => self.ppu.write_ppu(addr, val),
 
 			0x50 => self.boot = val == 0,
 
@@ -52,6 +135,18 @@ impl GBIO {
 	pub fn read_byte(&self, addr: u8) -> u8 {
 		match addr {
 			0x00 => self.joypad.read_joypad(),
+
+			0x01 => self.serial.read_sb(),
+			0x02 => self.serial.read_sc(),
+
+			0x04 => self.timer.read_div(),
+			0x05 => self.timer.read_tima(),
+			0x06 => self.timer.read_tma(),
+			0x07 => self.timer.read_tac(),
+
+			0x40 ... 0x45 => self.ppu.read_ppu(addr),
+			0x47 ... 0x4B => self.ppu.read_ppu(addr),
+
 			0x0F => self.interrupt.read_flags(),
 			0xFF => self.interrupt.read_enable(),
 			_ => panic!("Unimplemented IO Read {:02X}", addr)
@@ -81,7 +176,78 @@ const INTERRUPT_TIMER_MASK  : u8 = 0b0000_0100;
 const INTERRUPT_SERIAL_MASK : u8 = 0b0000_1000;
 const INTERRUPT_JOYPAD_MASK : u8 = 0b0001_0000;
 
+// One of the five interrupt sources, in their fixed dispatch priority order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptSource {
+	VBlank,
+	LcdStat,
+	Timer,
+	Serial,
+	Joypad,
+}
+
+impl InterruptSource {
+	// Address `Cpu::step` should jump to when servicing this interrupt
+	pub fn vector(self) -> u16 {
+		match self {
+			InterruptSource::VBlank  => 0x40,
+			InterruptSource::LcdStat => 0x48,
+			InterruptSource::Timer   => 0x50,
+			InterruptSource::Serial  => 0x58,
+			InterruptSource::Joypad  => 0x60,
+		}
+	}
+}
+
 impl Interrupt {
+	// Highest-priority interrupt that is both enabled and flagged, in
+	// vblank > lcdstat > timer > serial > joypad order. `Cpu::step` checks
+	// this after every instruction to know whether to dispatch (when IME is
+	// set) or wake up from HALT (regardless of IME).
+	pub fn pending(&self) -> Option<InterruptSource> {
+		if self.enabled_vblank && self.flagged_vblank {
+			Some(InterruptSource::VBlank)
+		} else if self.enabled_lcdstat && self.flagged_lcdstat {
+			Some(InterruptSource::LcdStat)
+		} else if self.enabled_timer && self.flagged_timer {
+			Some(InterruptSource::Timer)
+		} else if self.enabled_serial && self.flagged_serial {
+			Some(InterruptSource::Serial)
+		} else if self.enabled_joypad && self.flagged_joypad {
+			Some(InterruptSource::Joypad)
+		} else {
+			None
+		}
+	}
+
+	// Clears the flag for `source`; called by the dispatch routine once it
+	// has pushed PC and jumped to the vector
+	pub fn acknowledge(&mut self, source: InterruptSource) {
+		match source {
+			InterruptSource::VBlank  => self.flagged_vblank = false,
+			InterruptSource::LcdStat => self.flagged_lcdstat = false,
+			InterruptSource::Timer   => self.flagged_timer = false,
+			InterruptSource::Serial  => self.flagged_serial = false,
+			InterruptSource::Joypad  => self.flagged_joypad = false,
+		}
+	}
+
+	pub fn flag_vblank(&mut self) {
+		self.flagged_vblank = true;
+	}
+
+	pub fn flag_lcdstat(&mut self) {
+		self.flagged_lcdstat = true;
+	}
+
+	pub fn flag_timer(&mut self) {
+		self.flagged_timer = true;
+	}
+
+	pub fn flag_serial(&mut self) {
+		self.flagged_serial = true;
+	}
+
 	pub fn write_flags(&mut self, val: u8) {
 		if (val & INTERRUPT_VBLANK_MASK) != 0 {
 			self.flagged_vblank = true;
@@ -252,10 +418,114 @@ impl Joypad {
 }
 
 // Link cable!
+
+// Roughly how long an internal-clock serial transfer takes: 8 bits shifted
+// out at the ~8192 Hz internal clock
+const SERIAL_TRANSFER_CYCLES: u16 = 8 * 512;
+
+const SC_TRANSFER_START_MASK: u8 = 0b1000_0000;
+const SC_INTERNAL_CLOCK_MASK: u8 = 0b0000_0001;
+
+// Where a transferred serial byte ends up. The default prints to stdout,
+// which is exactly how Blargg's `cpu_instrs` test ROMs report pass/fail.
+pub trait SerialWriter {
+	fn write_byte(&mut self, byte: u8);
+}
+
+pub struct StdoutSerialWriter;
+
+impl SerialWriter for StdoutSerialWriter {
+	fn write_byte(&mut self, byte: u8) {
+		print!("{}", byte as char);
+		io::stdout().flush().ok();
+	}
+}
+
 struct SerialData {
+	data: u8, // SB, 0xFF01
+
+	transfer_active: bool,
+	remaining_cycles: u16,
+	internal_clock: bool, // Last value written to SC's clock-source bit
+
+	writer: Box<SerialWriter>,
+}
+
+impl SerialData {
+	fn new(writer: Box<SerialWriter>) -> SerialData {
+		SerialData {
+			data: 0,
+			transfer_active: false,
+			remaining_cycles: 0,
+			internal_clock: false,
+			writer: writer,
+		}
+	}
 
+	// Advances the in-flight transfer, if any, returning whether the serial
+	// interrupt should be raised as a result
+	fn step(&mut self, cycles: u32) -> bool {
+		if !self.transfer_active {
+			return false;
+		}
+
+		let cycles = cycles as u16;
+		self.remaining_cycles = self.remaining_cycles.saturating_sub(cycles);
+
+		if self.remaining_cycles == 0 {
+			self.transfer_active = false;
+			self.writer.write_byte(self.data);
+			true
+		} else {
+			false
+		}
+	}
+
+	fn write_sb(&mut self, val: u8) {
+		self.data = val;
+	}
+
+	fn read_sb(&self) -> u8 {
+		self.data
+	}
+
+	fn write_sc(&mut self, val: u8) {
+		let start = (val & SC_TRANSFER_START_MASK) != 0;
+		self.internal_clock = (val & SC_INTERNAL_CLOCK_MASK) != 0;
+
+		// The external-clock case (the link partner drives the shift) has no
+		// partner to shift with here, so only internal-clock transfers run
+		if start && self.internal_clock {
+			self.transfer_active = true;
+			self.remaining_cycles = SERIAL_TRANSFER_CYCLES;
+		}
+	}
+
+	fn read_sc(&self) -> u8 {
+		0b0111_1110 + // Unused bits read back as 1
+		(if self.transfer_active { SC_TRANSFER_START_MASK } else { 0 }) +
+		(if self.internal_clock { SC_INTERNAL_CLOCK_MASK } else { 0 })
+	}
 }
 
-struct PPU {
+// An in-flight OAM DMA transfer triggered by a write to 0xFF46: copies 0xA0
+// bytes from `base << 8` into OAM, one byte per machine cycle
+struct DmaState {
+	base: u8,
+	remaining_cycles: u8,
+}
 
+impl DmaState {
+	fn new(base: u8) -> DmaState {
+		DmaState {
+			base: base,
+			remaining_cycles: 0xA0,
+		}
+	}
+
+	// Address of the next byte to copy, counting up from `base << 8` as
+	// `remaining_cycles` counts down from 0xA0
+	fn source_address(&self) -> u16 {
+		((self.base as u16) << 8) + (0xA0 - self.remaining_cycles as u16)
+	}
 }
\ No newline at end of file
@@ -0,0 +1,305 @@
+// Cartridge mapper (MBC) abstraction, selected at load time from the
+// cartridge header at 0x147
+
+pub trait Mapper {
+	fn read_rom(&self, addr: u16) -> u8;
+	fn write_rom(&mut self, addr: u16, val: u8);
+
+	fn read_ram(&self, addr: u16) -> u8;
+	fn write_ram(&mut self, addr: u16, val: u8);
+}
+
+// Builds the right `Mapper` for `rom`, reading its type off the header
+pub fn from_header(rom: Box<[u8]>) -> Result<Box<Mapper>, String> {
+	if rom.len() < 0x150 {
+		return Err(format!("Cartridge is too short to contain a header ({} bytes)", rom.len()));
+	}
+
+	let ram_size = ram_size_from_header(rom[0x149]);
+
+	match rom[0x147] {
+		0x00 => Ok(Box::new(NoMbc::new(rom))),
+
+		0x01 | 0x02 | 0x03 => Ok(Box::new(Mbc1::new(rom, ram_size))),
+
+		0x0F | 0x10 | 0x11 | 0x12 | 0x13 => Ok(Box::new(Mbc3::new(rom, ram_size))),
+
+		other => Err(format!("Unsupported cartridge type 0x{:02X}", other)),
+	}
+}
+
+fn ram_size_from_header(val: u8) -> usize {
+	match val {
+		0x00 => 0,
+		0x01 => 2 * 1024,
+		0x02 => 8 * 1024,
+		0x03 => 32 * 1024,
+		0x04 => 128 * 1024,
+		0x05 => 64 * 1024,
+		_ => 0,
+	}
+}
+
+// 32 KB, no banking and no external RAM
+pub struct NoMbc {
+	rom: Box<[u8]>,
+}
+
+impl NoMbc {
+	pub fn new(rom: Box<[u8]>) -> NoMbc {
+		NoMbc { rom: rom }
+	}
+}
+
+impl Mapper for NoMbc {
+	fn read_rom(&self, addr: u16) -> u8 {
+		self.rom[addr as usize]
+	}
+
+	fn write_rom(&mut self, _addr: u16, _val: u8) {
+		// No registers to write, ROM is fixed
+	}
+
+	fn read_ram(&self, _addr: u16) -> u8 {
+		0xFF
+	}
+
+	fn write_ram(&mut self, _addr: u16, _val: u8) {
+	}
+}
+
+#[derive(Clone, Copy)]
+enum Mbc1Mode {
+	Rom, // 0x4000-0x5FFF selects ROM bank bits 5-6
+	Ram, // 0x4000-0x5FFF selects the RAM bank
+}
+
+pub struct Mbc1 {
+	rom: Box<[u8]>,
+	ram: Box<[u8]>,
+
+	ram_enabled: bool,
+	rom_bank_low: u8, // 5 bits, register at 0x2000-0x3FFF
+	bank_upper_bits: u8, // 2 bits, register at 0x4000-0x5FFF
+	mode: Mbc1Mode,
+}
+
+impl Mbc1 {
+	pub fn new(rom: Box<[u8]>, ram_size: usize) -> Mbc1 {
+		Mbc1 {
+			rom: rom,
+			ram: vec![0u8 ; ram_size].into_boxed_slice(),
+
+			ram_enabled: false,
+			rom_bank_low: 1,
+			bank_upper_bits: 0,
+			mode: Mbc1Mode::Rom,
+		}
+	}
+
+	fn rom_bank(&self) -> usize {
+		let low = if self.rom_bank_low == 0 { 1 } else { self.rom_bank_low };
+
+		match self.mode {
+			Mbc1Mode::Rom => (((self.bank_upper_bits as usize) << 5) | (low as usize)),
+			Mbc1Mode::Ram => low as usize,
+		}
+	}
+
+	fn ram_bank(&self) -> usize {
+		match self.mode {
+			Mbc1Mode::Ram => self.bank_upper_bits as usize,
+			Mbc1Mode::Rom => 0,
+		}
+	}
+}
+
+impl Mapper for Mbc1 {
+	fn read_rom(&self, addr: u16) -> u8 {
+		match addr {
+			0x0000 ... 0x3FFF => self.rom[addr as usize],
+
+			_ => {
+				let offset = self.rom_bank() * 0x4000 + (addr - 0x4000) as usize;
+				self.rom[offset % self.rom.len()]
+			}
+		}
+	}
+
+	fn write_rom(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000 ... 0x1FFF => self.ram_enabled = (val & 0x0F) == 0x0A,
+			0x2000 ... 0x3FFF => self.rom_bank_low = val & 0b0001_1111,
+			0x4000 ... 0x5FFF => self.bank_upper_bits = val & 0b0000_0011,
+			_ => self.mode = if (val & 1) != 0 { Mbc1Mode::Ram } else { Mbc1Mode::Rom },
+		}
+	}
+
+	fn read_ram(&self, addr: u16) -> u8 {
+		if !self.ram_enabled || self.ram.is_empty() {
+			return 0xFF;
+		}
+
+		let offset = self.ram_bank() * 0x2000 + addr as usize;
+		self.ram[offset % self.ram.len()]
+	}
+
+	fn write_ram(&mut self, addr: u16, val: u8) {
+		if !self.ram_enabled || self.ram.is_empty() {
+			return;
+		}
+
+		let len = self.ram.len();
+		let offset = self.ram_bank() * 0x2000 + addr as usize;
+		self.ram[offset % len] = val;
+	}
+}
+
+// Registers making up the MBC3 real-time clock
+#[derive(Default, Clone, Copy)]
+struct RtcRegisters {
+	seconds: u8,
+	minutes: u8,
+	hours: u8,
+	day_low: u8,
+	day_high: u8, // Bit 0 is day counter bit 8, bit 6 is halt, bit 7 is day counter carry
+}
+
+pub struct Mbc3 {
+	rom: Box<[u8]>,
+	ram: Box<[u8]>,
+
+	ram_and_timer_enabled: bool,
+	rom_bank: u8, // 7 bits, register at 0x2000-0x3FFF, 0 reads back as 1
+	ram_bank_or_rtc_register: u8, // register at 0x4000-0x5FFF: 0x00-0x03 selects RAM, 0x08-0x0C selects an RTC register
+
+	rtc: RtcRegisters,
+	latched_rtc: RtcRegisters,
+	latch_write_in_progress: bool, // Last byte written to 0x6000-0x7FFF was a 0x00, waiting for the latching 0x01
+}
+
+impl Mbc3 {
+	pub fn new(rom: Box<[u8]>, ram_size: usize) -> Mbc3 {
+		Mbc3 {
+			rom: rom,
+			ram: vec![0u8 ; ram_size].into_boxed_slice(),
+
+			ram_and_timer_enabled: false,
+			rom_bank: 1,
+			ram_bank_or_rtc_register: 0,
+
+			rtc: RtcRegisters::default(),
+			latched_rtc: RtcRegisters::default(),
+			latch_write_in_progress: false,
+		}
+	}
+
+	fn rom_bank(&self) -> usize {
+		if self.rom_bank == 0 { 1 } else { self.rom_bank as usize }
+	}
+}
+
+impl Mapper for Mbc3 {
+	fn read_rom(&self, addr: u16) -> u8 {
+		match addr {
+			0x0000 ... 0x3FFF => self.rom[addr as usize],
+
+			_ => {
+				let offset = self.rom_bank() * 0x4000 + (addr - 0x4000) as usize;
+				self.rom[offset % self.rom.len()]
+			}
+		}
+	}
+
+	fn write_rom(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000 ... 0x1FFF => self.ram_and_timer_enabled = (val & 0x0F) == 0x0A,
+			0x2000 ... 0x3FFF => self.rom_bank = val & 0b0111_1111,
+			0x4000 ... 0x5FFF => self.ram_bank_or_rtc_register = val,
+
+			_ => {
+				if val == 0x00 {
+					self.latch_write_in_progress = true;
+				} else if val == 0x01 && self.latch_write_in_progress {
+					self.latched_rtc = self.rtc;
+					self.latch_write_in_progress = false;
+				} else {
+					self.latch_write_in_progress = false;
+				}
+			}
+		}
+	}
+
+	fn read_ram(&self, addr: u16) -> u8 {
+		if !self.ram_and_timer_enabled {
+			return 0xFF;
+		}
+
+		match self.ram_bank_or_rtc_register {
+			0x00 ... 0x03 => {
+				if self.ram.is_empty() {
+					return 0xFF;
+				}
+				let offset = (self.ram_bank_or_rtc_register as usize) * 0x2000 + addr as usize;
+				self.ram[offset % self.ram.len()]
+			}
+
+			0x08 => self.latched_rtc.seconds,
+			0x09 => self.latched_rtc.minutes,
+			0x0A => self.latched_rtc.hours,
+			0x0B => self.latched_rtc.day_low,
+			0x0C => self.latched_rtc.day_high,
+
+			_ => 0xFF,
+		}
+	}
+
+	fn write_ram(&mut self, addr: u16, val: u8) {
+		if !self.ram_and_timer_enabled {
+			return;
+		}
+
+		match self.ram_bank_or_rtc_register {
+			0x00 ... 0x03 => {
+				if self.ram.is_empty() {
+					return;
+				}
+				let len = self.ram.len();
+				let offset = (self.ram_bank_or_rtc_register as usize) * 0x2000 + addr as usize;
+				self.ram[offset % len] = val;
+			}
+
+			0x08 => self.rtc.seconds = val,
+			0x09 => self.rtc.minutes = val,
+			0x0A => self.rtc.hours = val,
+			0x0B => self.rtc.day_low = val,
+			0x0C => self.rtc.day_high = val,
+
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mbc1_treats_rom_bank_register_zero_as_bank_one() {
+		let mut rom = vec![0u8 ; 0x8000];
+		rom[0x4000] = 0xAB; // First byte of bank 1
+		let mut mapper = Mbc1::new(rom.into_boxed_slice(), 0);
+
+		mapper.write_rom(0x2000, 0x00); // Selecting "bank 0" ...
+		assert_eq!(mapper.read_rom(0x4000), 0xAB); // ... reads bank 1 instead
+
+		mapper.write_rom(0x2000, 0x20); // Masked down to 0 (0x20 & 0x1F == 0)
+		assert_eq!(mapper.read_rom(0x4000), 0xAB);
+	}
+
+	#[test]
+	fn from_header_rejects_roms_too_short_for_a_header() {
+		let rom = vec![0u8 ; 0x100].into_boxed_slice();
+		assert!(from_header(rom).is_err());
+	}
+}
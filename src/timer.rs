@@ -0,0 +1,152 @@
+// DIV/TIMA/TMA/TAC at 0xFF04-0xFF07
+
+// Which bit of the 16-bit internal counter TAC's clock select watches,
+// indexed by the two select bits (4096/262144/65536/16384 Hz)
+const TAC_BIT_FOR_SELECT: [u8 ; 4] = [9, 3, 5, 7];
+
+pub struct Timer {
+	internal_counter: u16, // DIV is just the upper 8 bits of this
+	tima: u8,
+	tma: u8,
+
+	enabled: bool,
+	input_clock_select: u8, // 0-3, indexes TAC_BIT_FOR_SELECT
+
+	previous_bit: bool, // Watched counter bit (ANDed with `enabled`), before this step
+	reload_pending: bool, // TIMA overflowed last cycle; the TMA reload (and interrupt) land one cycle late, and writes to TIMA in between are dropped
+}
+
+impl Default for Timer {
+	fn default() -> Timer {
+		Timer {
+			internal_counter: 0,
+			tima: 0,
+			tma: 0,
+
+			enabled: false,
+			input_clock_select: 0,
+
+			previous_bit: false,
+			reload_pending: false,
+		}
+	}
+}
+
+impl Timer {
+	// Advances the timer by `cycles` T-cycles, returning whether the timer
+	// interrupt should be raised as a result
+	pub fn step(&mut self, cycles: u32) -> bool {
+		let mut interrupt = false;
+
+		for _ in 0..cycles {
+			interrupt |= self.tick();
+		}
+
+		interrupt
+	}
+
+	fn tick(&mut self) -> bool {
+		let mut interrupt = false;
+
+		if self.reload_pending {
+			self.tima = self.tma;
+			self.reload_pending = false;
+			interrupt = true;
+		}
+
+		self.internal_counter = self.internal_counter.wrapping_add(1);
+
+		let bit_index = TAC_BIT_FOR_SELECT[self.input_clock_select as usize];
+		let bit = self.enabled && ((self.internal_counter >> bit_index) & 1) != 0;
+
+		if self.previous_bit && !bit {
+			let (new_tima, overflowed) = self.tima.overflowing_add(1);
+			self.tima = new_tima;
+
+			if overflowed {
+				self.reload_pending = true;
+			}
+		}
+		self.previous_bit = bit;
+
+		interrupt
+	}
+
+	// DIV resets to 0 on any write, regardless of the written value
+	pub fn write_div(&mut self, _val: u8) {
+		self.internal_counter = 0;
+	}
+
+	pub fn read_div(&self) -> u8 {
+		(self.internal_counter >> 8) as u8
+	}
+
+	// Ignored while a TMA reload is pending, per hardware's one-cycle delay
+	pub fn write_tima(&mut self, val: u8) {
+		if !self.reload_pending {
+			self.tima = val;
+		}
+	}
+
+	pub fn read_tima(&self) -> u8 {
+		self.tima
+	}
+
+	pub fn write_tma(&mut self, val: u8) {
+		self.tma = val;
+	}
+
+	pub fn read_tma(&self) -> u8 {
+		self.tma
+	}
+
+	pub fn write_tac(&mut self, val: u8) {
+		self.input_clock_select = val & 0b0000_0011;
+		self.enabled = (val & 0b0000_0100) != 0;
+	}
+
+	pub fn read_tac(&self) -> u8 {
+		0b1111_1000 + self.input_clock_select + (if self.enabled { 0b0000_0100 } else { 0 })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn timer_with_fast_clock(tima: u8, tma: u8) -> Timer {
+		let mut timer = Timer::default();
+		timer.write_tac(0b0000_0101); // Enabled, select 1 (bit 3, falls every 16 ticks)
+		timer.write_tima(tima);
+		timer.write_tma(tma);
+		timer
+	}
+
+	#[test]
+	fn tima_reload_and_interrupt_land_one_cycle_after_overflow() {
+		let mut timer = timer_with_fast_clock(0xFF, 0x12);
+
+		// The tick that overflows TIMA wraps it to 0 but doesn't reload or interrupt yet
+		let interrupt = timer.step(16);
+		assert_eq!(timer.read_tima(), 0);
+		assert!(!interrupt);
+
+		// Writes landing in this one-cycle window are dropped
+		timer.write_tima(0x99);
+		assert_eq!(timer.read_tima(), 0);
+
+		let interrupt = timer.step(1);
+		assert_eq!(timer.read_tima(), 0x12);
+		assert!(interrupt);
+	}
+
+	#[test]
+	fn div_resets_to_zero_on_any_write() {
+		let mut timer = Timer::default();
+		timer.step(256); // DIV is the counter's upper byte, so it first ticks at 256
+		assert_ne!(timer.read_div(), 0);
+
+		timer.write_div(0xFF);
+		assert_eq!(timer.read_div(), 0);
+	}
+}
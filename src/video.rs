@@ -1,11 +1,27 @@
 use mem_map;
 
 const N_SPRITES: usize = (mem_map::SPRITE_RAM_LENGTH as usize) / 4;
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+pub const SCREEN_PIXELS: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+// Dot (T-cycle) timings of the STAT mode machine, per scanline
+const OAM_SEARCH_DOTS: u16 = 80;
+const PIXEL_TRANSFER_DOTS: u16 = 172;
+const HBLANK_DOTS: u16 = 456 - OAM_SEARCH_DOTS - PIXEL_TRANSFER_DOTS;
+const DOTS_PER_LINE: u16 = 456;
+
+const LAST_VISIBLE_LINE: u8 = 143;
+const LAST_LINE: u8 = 153;
 
 pub struct PPU {
 	sprite_ram: [Sprite ; N_SPRITES],
 	pub vram: [u8 ; mem_map::VRAM_LENGTH as usize],
 
+	framebuffer: Box<[Color ; SCREEN_PIXELS]>,
+
 	// LCD Control
 	lcd_display_enabled: bool,
 	window_tile_map_address: bool,
@@ -16,7 +32,15 @@ pub struct PPU {
 	sprites_enabled: bool,
 	background_enabled: bool,
 
-	//TODO: LCDC status
+	// LCD status
+	mode: PpuMode,
+	dot_counter: u16,
+	window_line_counter: u8, // Lines of the window actually drawn so far this frame
+
+	mode0_interrupt_enabled: bool,
+	mode1_interrupt_enabled: bool,
+	mode2_interrupt_enabled: bool,
+	lyc_interrupt_enabled: bool,
 
 	scroll_y: u8,
 	scroll_x: u8,
@@ -31,6 +55,34 @@ pub struct PPU {
 	window_x_position: u8,
 }
 
+// The four STAT modes, numbered as they appear in bits 0-1 of the STAT register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PpuMode {
+	HBlank,      // 0
+	VBlank,      // 1
+	OamSearch,   // 2
+	PixelTransfer, // 3
+}
+
+impl PpuMode {
+	fn to_bits(self) -> u8 {
+		match self {
+			PpuMode::HBlank => 0b00,
+			PpuMode::VBlank => 0b01,
+			PpuMode::OamSearch => 0b10,
+			PpuMode::PixelTransfer => 0b11,
+		}
+	}
+}
+
+// Interrupts the PPU would like raised as a result of a `step`, left for the
+// caller (`GBIO`, which owns both the PPU and the `Interrupt` flags) to apply
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PpuInterrupts {
+	pub vblank: bool,
+	pub lcdstat: bool,
+}
+
 impl Default for PPU {
 	fn default() -> PPU {
 		PPU {
@@ -38,6 +90,8 @@ impl Default for PPU {
 			sprite_ram: [Sprite::default() ; N_SPRITES],
 			vram: [0u8 ; mem_map::VRAM_LENGTH as usize],
 
+			framebuffer: Box::new([Color::White ; SCREEN_PIXELS]),
+
 			lcd_display_enabled: false,
 			window_tile_map_address: false,
 			window_enabled: false,
@@ -47,6 +101,15 @@ impl Default for PPU {
 			sprites_enabled: false,
 			background_enabled: false,
 
+			mode: PpuMode::OamSearch,
+			dot_counter: 0,
+			window_line_counter: 0,
+
+			mode0_interrupt_enabled: false,
+			mode1_interrupt_enabled: false,
+			mode2_interrupt_enabled: false,
+			lyc_interrupt_enabled: false,
+
 			scroll_y: 0,
 			scroll_x: 0,
 
@@ -72,13 +135,293 @@ const LCDC_SPRITE_SIZE_MASK                : u8 = 0b0000_0100;
 const LCDC_SPRITE_DISPLAY_ENABLE_MASK      : u8 = 0b0000_0010;
 const LCDC_BG_DISPLAY_ENABLE_MASK          : u8 = 0b0000_0001;
 
+// STAT masks
+const STAT_LYC_INTERRUPT_MASK  : u8 = 0b0100_0000;
+const STAT_MODE2_INTERRUPT_MASK: u8 = 0b0010_0000;
+const STAT_MODE1_INTERRUPT_MASK: u8 = 0b0001_0000;
+const STAT_MODE0_INTERRUPT_MASK: u8 = 0b0000_1000;
+const STAT_COINCIDENCE_MASK    : u8 = 0b0000_0100;
+const STAT_MODE_MASK           : u8 = 0b0000_0011;
+
 impl PPU {
+	// Read-only access to the last completed frame, for the frontend
+	pub fn framebuffer(&self) -> &[Color ; SCREEN_PIXELS] {
+		&self.framebuffer
+	}
+
+	// Advances the STAT mode machine by `cycles` dots, rendering into the
+	// framebuffer as scanlines complete pixel transfer, and returns whichever
+	// interrupts should be raised as a result
+	pub fn step(&mut self, cycles: u32) -> PpuInterrupts {
+		let mut interrupts = PpuInterrupts::default();
+
+		if !self.lcd_display_enabled {
+			return interrupts;
+		}
+
+		self.dot_counter += cycles as u16;
+
+		loop {
+			let threshold = match self.mode {
+				PpuMode::OamSearch => OAM_SEARCH_DOTS,
+				PpuMode::PixelTransfer => PIXEL_TRANSFER_DOTS,
+				PpuMode::HBlank => HBLANK_DOTS,
+				PpuMode::VBlank => DOTS_PER_LINE,
+			};
+
+			if self.dot_counter < threshold {
+				break;
+			}
+
+			self.dot_counter -= threshold;
+			self.advance_mode(&mut interrupts);
+		}
+
+		interrupts
+	}
+
+	// Runs at the end of the current mode's dot budget: renders, moves LY
+	// along and switches to the next STAT mode, raising the relevant
+	// mode/coincidence STAT interrupts as it goes
+	fn advance_mode(&mut self, interrupts: &mut PpuInterrupts) {
+		match self.mode {
+			PpuMode::OamSearch => {
+				self.mode = PpuMode::PixelTransfer;
+			}
+
+			PpuMode::PixelTransfer => {
+				self.render_scanline();
+				self.mode = PpuMode::HBlank;
+				if self.mode0_interrupt_enabled {
+					interrupts.lcdstat = true;
+				}
+			}
+
+			PpuMode::HBlank => {
+				self.end_of_line(interrupts);
+
+				if self.lcdc_y_coordinate > LAST_VISIBLE_LINE {
+					self.mode = PpuMode::VBlank;
+					self.window_line_counter = 0;
+					interrupts.vblank = true;
+					if self.mode1_interrupt_enabled {
+						interrupts.lcdstat = true;
+					}
+				} else {
+					self.mode = PpuMode::OamSearch;
+					if self.mode2_interrupt_enabled {
+						interrupts.lcdstat = true;
+					}
+				}
+			}
+
+			PpuMode::VBlank => {
+				self.end_of_line(interrupts);
+
+				if self.lcdc_y_coordinate > LAST_LINE {
+					self.lcdc_y_coordinate = 0;
+					self.check_coincidence(interrupts);
+					self.mode = PpuMode::OamSearch;
+					if self.mode2_interrupt_enabled {
+						interrupts.lcdstat = true;
+					}
+				}
+			}
+		}
+	}
+
+	fn end_of_line(&mut self, interrupts: &mut PpuInterrupts) {
+		self.lcdc_y_coordinate = self.lcdc_y_coordinate.wrapping_add(1);
+		self.check_coincidence(interrupts);
+	}
+
+	fn check_coincidence(&mut self, interrupts: &mut PpuInterrupts) {
+		if self.lyc_interrupt_enabled && self.lcdc_y_coordinate == self.ly_compare {
+			interrupts.lcdstat = true;
+		}
+	}
+
+	fn write_stat(&mut self, val: u8) {
+		self.lyc_interrupt_enabled = (val & STAT_LYC_INTERRUPT_MASK) != 0;
+		self.mode2_interrupt_enabled = (val & STAT_MODE2_INTERRUPT_MASK) != 0;
+		self.mode1_interrupt_enabled = (val & STAT_MODE1_INTERRUPT_MASK) != 0;
+		self.mode0_interrupt_enabled = (val & STAT_MODE0_INTERRUPT_MASK) != 0;
+	}
+
+	fn read_stat(&self) -> u8 {
+		0b1000_0000 + // Unused bit, always reads as 1 on hardware
+		(if self.lyc_interrupt_enabled { STAT_LYC_INTERRUPT_MASK } else { 0 }) +
+		(if self.mode2_interrupt_enabled { STAT_MODE2_INTERRUPT_MASK } else { 0 }) +
+		(if self.mode1_interrupt_enabled { STAT_MODE1_INTERRUPT_MASK } else { 0 }) +
+		(if self.mode0_interrupt_enabled { STAT_MODE0_INTERRUPT_MASK } else { 0 }) +
+		(if self.lcdc_y_coordinate == self.ly_compare { STAT_COINCIDENCE_MASK } else { 0 }) +
+		(self.mode.to_bits() & STAT_MODE_MASK)
+	}
+
+	fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+		self.framebuffer[y * SCREEN_WIDTH + x] = color;
+	}
+
+	fn vram_read(&self, addr: u16) -> u8 {
+		self.vram[(addr - 0x8000) as usize]
+	}
+
+	// Address of the first of the 16 bytes describing `tile_number`, honouring
+	// the signed 0x8800 addressing mode relative to 0x9000
+	fn tile_data_address(&self, tile_number: u8) -> u16 {
+		if self.background_window_tile_data_address {
+			0x8000 + (tile_number as u16) * 16
+		} else {
+			(0x9000i32 + (tile_number as i8 as i32) * 16) as u16
+		}
+	}
+
+	// Color index (0-3) of the pixel at (col, row) within the tile starting at `tile_addr`
+	fn tile_pixel(&self, tile_addr: u16, row: u8, col: u8) -> u8 {
+		let low = self.vram_read(tile_addr + (row as u16) * 2);
+		let high = self.vram_read(tile_addr + (row as u16) * 2 + 1);
+
+		let bit = 7 - col;
+		let lo_bit = (low >> bit) & 1;
+		let hi_bit = (high >> bit) & 1;
+
+		(hi_bit << 1) | lo_bit
+	}
+
+	fn render_scanline(&mut self) {
+		let mut bg_color_index = [0u8 ; SCREEN_WIDTH];
+
+		if self.background_enabled {
+			self.render_background_line(&mut bg_color_index);
+		}
+
+		let mut window_drawn = false;
+		if self.window_enabled && self.lcdc_y_coordinate >= self.window_y_position {
+			self.render_window_line(&mut bg_color_index);
+			window_drawn = true;
+		}
+
+		if self.sprites_enabled {
+			self.render_sprites_line(&bg_color_index);
+		}
+
+		if window_drawn {
+			self.window_line_counter += 1;
+		}
+	}
+
+	fn render_background_line(&mut self, bg_color_index: &mut [u8 ; SCREEN_WIDTH]) {
+		let map_base: u16 = if self.background_tile_map_address { 0x9C00 } else { 0x9800 };
+		let y = self.lcdc_y_coordinate.wrapping_add(self.scroll_y);
+		let tile_row = (y / 8) as u16;
+		let line = self.lcdc_y_coordinate;
+
+		for screen_x in 0..SCREEN_WIDTH {
+			let x = (screen_x as u8).wrapping_add(self.scroll_x);
+			let tile_col = (x / 8) as u16;
+
+			let map_addr = map_base + tile_row * 32 + tile_col;
+			let tile_number = self.vram_read(map_addr);
+			let tile_addr = self.tile_data_address(tile_number);
+
+			let color_index = self.tile_pixel(tile_addr, y % 8, x % 8);
+			bg_color_index[screen_x] = color_index;
+
+			let color = self.background_palette[color_index as usize];
+			self.set_pixel(screen_x, line as usize, color);
+		}
+	}
+
+	fn render_window_line(&mut self, bg_color_index: &mut [u8 ; SCREEN_WIDTH]) {
+		let map_base: u16 = if self.window_tile_map_address { 0x9C00 } else { 0x9800 };
+		let y = self.window_line_counter;
+		let tile_row = (y / 8) as u16;
+		let line = self.lcdc_y_coordinate;
+
+		// Window X is stored offset by 7, per hardware convention
+		let window_start_x = (self.window_x_position as i16) - 7;
+
+		for screen_x in 0..SCREEN_WIDTH {
+			let x = screen_x as i16 - window_start_x;
+			if x < 0 {
+				continue;
+			}
+			let x = x as u8;
+			let tile_col = (x / 8) as u16;
+
+			let map_addr = map_base + tile_row * 32 + tile_col;
+			let tile_number = self.vram_read(map_addr);
+			let tile_addr = self.tile_data_address(tile_number);
+
+			let color_index = self.tile_pixel(tile_addr, y % 8, x % 8);
+			bg_color_index[screen_x] = color_index;
+
+			let color = self.background_palette[color_index as usize];
+			self.set_pixel(screen_x, line as usize, color);
+		}
+	}
+
+	fn render_sprites_line(&mut self, bg_color_index: &[u8 ; SCREEN_WIDTH]) {
+		let line = self.lcdc_y_coordinate;
+		let sprite_height: u8 = if self.sprite_size { 16 } else { 8 };
+
+		// Hardware picks the first 10 sprites intersecting the line by OAM
+		// index, regardless of X, so truncate before sorting for draw order
+		let mut visible: Vec<(usize, Sprite)> = self.sprite_ram.iter()
+			.cloned()
+			.enumerate()
+			.filter(|&(_, ref sprite)| {
+				let screen_line = line.wrapping_add(16);
+				screen_line >= sprite.position_y && screen_line < sprite.position_y.wrapping_add(sprite_height)
+			})
+			.collect();
+		visible.truncate(MAX_SPRITES_PER_LINE);
+
+		// Lower X coordinate draws on top, so draw highest-X first and let
+		// lower-X sprites painted later overwrite them; on an X tie, the
+		// lower OAM index wins, so it must be drawn later too
+		visible.sort_by(|a, b| b.1.position_x.cmp(&a.1.position_x).then(b.0.cmp(&a.0)));
+
+		for &(_, ref sprite) in visible.iter() {
+			let mut row = line.wrapping_add(16).wrapping_sub(sprite.position_y);
+			if sprite.flip_y {
+				row = sprite_height - 1 - row;
+			}
+
+			let tile_number = if sprite_height == 16 { sprite.tile_number & 0xFE } else { sprite.tile_number };
+			let tile_addr = 0x8000u16 + (tile_number as u16) * 16;
+
+			for col in 0..8u8 {
+				let screen_x = (sprite.position_x as i16) - 8 + (col as i16);
+				if screen_x < 0 || screen_x >= SCREEN_WIDTH as i16 {
+					continue;
+				}
+
+				let sample_col = if sprite.flip_x { 7 - col } else { col };
+				let color_index = self.tile_pixel(tile_addr, row, sample_col);
+				if color_index == 0 {
+					continue; // Transparent
+				}
+
+				if sprite.priority && bg_color_index[screen_x as usize] != 0 {
+					continue; // Background has priority over this sprite
+				}
+
+				let color = self.object_palettes[sprite.palette][color_index as usize];
+				self.set_pixel(screen_x as usize, line as usize, color);
+			}
+		}
+	}
+
 	pub fn write_ppu(&mut self, addr: u8, val: u8) {
 		match addr {
 			0x40 => self.write_lcd_control(val),
+			0x41 => self.write_stat(val),
 			0x42 => self.scroll_y = val,
 			0x43 => self.scroll_x = val,
 
+			0x44 => self.lcdc_y_coordinate = 0, // Writes reset LY on real hardware
+
 			0x45 => self.ly_compare = val,
 
 			0x47 => Self::write_palette(&mut self.background_palette, val),
@@ -95,6 +438,8 @@ impl PPU {
 
 	pub fn read_ppu(&self, addr: u8) -> u8 {
 		match addr {
+			0x40 => self.read_lcd_control(),
+			0x41 => self.read_stat(),
 			0x42 => self.scroll_y,
 			0x43 => self.scroll_x,
 			0x44 => self.lcdc_y_coordinate,
@@ -126,11 +471,13 @@ impl PPU {
 				sprite.priority = if (val & 0b1000_0000) != 0 { true } else { false };
 				sprite.flip_y = if (val & 0b0100_0000) != 0 { true } else { false };
 				sprite.flip_x = if (val & 0b0010_0000) != 0 { true } else { false };
-				sprite.palette = if (val & 0b1000_0000) != 0 { 1 } else { 0 };
+				sprite.palette = if (val & SPRITE_PALETTE_MASK) != 0 { 1 } else { 0 };
 			}
 
 			_ => {}
 		}
+
+		self.sprite_ram[sprite_index] = sprite;
 	}
 
 	pub fn read_sprite_entry(&self, addr: u8) -> u8 {
@@ -147,7 +494,7 @@ impl PPU {
 				if sprite.priority { SPRITE_PRIORITY_MASK } else { 0 } +
 				if sprite.flip_y { SPRITE_FLIP_Y_MASK } else { 0 } +
 				if sprite.flip_x { SPRITE_FLIP_X_MASK } else { 0 } +
-				(sprite.palette as u8),
+				((sprite.palette as u8) << 4),
 
 			_ => panic!("It's impossible that this happened, as there's no fifth byte to see"),
 		}
@@ -202,8 +549,8 @@ impl PPU {
 }
 
 // The 4 displayed colors on the Game Boy
-#[derive(Copy, Clone)]
-enum Color {
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Color {
 	White, LightGray, DarkGray, Black
 	// Well, more like green, other green, more green and greener
 	// but you get the idea